@@ -0,0 +1,213 @@
+use crate::rate_limit::{ClientIp, RateLimiter};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{rt, web, HttpRequest, HttpResponse, Responder};
+use rand::Rng;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const SESSION_COOKIE: &str = "session";
+const SESSION_ID_LEN: usize = 32;
+
+// Saves older than this are treated as gone; the cookie lives as long.
+const AUTOSAVE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const MAX_SAVE_BYTES: usize = 16 * 1024;
+
+// Total size of all stored saves. Once reached, new sessions are turned
+// away until old saves expire; existing sessions can still overwrite
+// theirs, so a flood of fresh sessions can't push out real players.
+const MAX_TOTAL_BYTES: usize = 64 * 1024 * 1024;
+
+// The frontend saves every few moves; this leaves room for fast play
+// while keeping one client from filling the store.
+const SAVES_PER_WINDOW: u32 = 60;
+const SAVE_WINDOW: Duration = Duration::from_secs(60);
+
+struct Entry {
+    saved: Instant,
+    game: String,
+}
+
+impl Entry {
+    fn size(session: &str, game: &str) -> usize {
+        session.len() + game.len()
+    }
+}
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+}
+
+impl Store {
+    fn remove(&mut self, session: &str) {
+        if let Some(entry) = self.entries.remove(session) {
+            self.bytes -= Entry::size(session, &entry.game);
+        }
+    }
+}
+
+// Latest save per browser session. The blob is whatever the frontend
+// serialized (its SaveGame), kept as JSON text without interpreting it.
+#[derive(Default)]
+pub struct Autosaves {
+    store: Mutex<Store>,
+}
+
+impl Autosaves {
+    // Returns false if the store is full.
+    fn store(&self, session: String, game: String) -> bool {
+        let mut store = self.store.lock().unwrap();
+        let replaced = store
+            .entries
+            .get(&session)
+            .map_or(0, |entry| Entry::size(&session, &entry.game));
+        let bytes = store.bytes - replaced + Entry::size(&session, &game);
+        if bytes > MAX_TOTAL_BYTES {
+            return false;
+        }
+        store.bytes = bytes;
+        let entry = Entry {
+            saved: Instant::now(),
+            game,
+        };
+        store.entries.insert(session, entry);
+        true
+    }
+
+    fn load(&self, session: &str) -> Option<String> {
+        let mut store = self.store.lock().unwrap();
+        match store.entries.get(session) {
+            Some(entry) if entry.saved.elapsed() < AUTOSAVE_TTL => Some(entry.game.clone()),
+            Some(_) => {
+                store.remove(session);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn drop_expired(&self) {
+        let mut store = self.store.lock().unwrap();
+        let Store { entries, bytes } = &mut *store;
+        entries.retain(|session, entry| {
+            let keep = entry.saved.elapsed() < AUTOSAVE_TTL;
+            if !keep {
+                *bytes -= Entry::size(session, &entry.game);
+            }
+            keep
+        });
+    }
+}
+
+// Expired saves are also dropped when read, but ones nobody comes back
+// for would otherwise hold their space until restart.
+pub fn spawn_sweeper(autosaves: web::Data<Autosaves>) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            autosaves.drop_expired();
+        }
+    });
+}
+
+pub fn limiter() -> RateLimiter {
+    RateLimiter::new(SAVES_PER_WINDOW, SAVE_WINDOW)
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig, limiter: web::Data<RateLimiter>) {
+    cfg.service(
+        web::resource("/autosave")
+            .app_data(limiter)
+            .app_data(web::JsonConfig::default().limit(MAX_SAVE_BYTES))
+            .route(web::get().to(restore))
+            .route(web::post().to(save)),
+    );
+}
+
+// Only ids shaped like the ones we hand out are honoured.
+fn session_id(req: &HttpRequest) -> Option<String> {
+    req.cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|id| id.len() == SESSION_ID_LEN && id.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn new_session_id() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+async fn save(
+    req: HttpRequest,
+    autosaves: web::Data<Autosaves>,
+    limiter: web::Data<RateLimiter>,
+    client_ip: web::Data<ClientIp>,
+    game: web::Json<Value>,
+) -> impl Responder {
+    // Checked before a session is minted so rejected requests leave
+    // nothing behind.
+    match client_ip.of(&req) {
+        Some(ip) if limiter.check(ip) => {}
+        Some(_) => return HttpResponse::TooManyRequests().finish(),
+        None => return HttpResponse::BadRequest().finish(),
+    }
+
+    let session = session_id(&req).unwrap_or_else(new_session_id);
+    if !autosaves.store(session.clone(), game.to_string()) {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+    // connection_info honours X-Forwarded-Proto, so this also holds behind
+    // a TLS-terminating proxy.
+    let secure = req.connection_info().scheme() == "https";
+    let cookie = Cookie::build(SESSION_COOKIE, session)
+        .path("/api")
+        .secure(secure)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::seconds(AUTOSAVE_TTL.as_secs() as i64))
+        .finish();
+    HttpResponse::NoContent().cookie(cookie).finish()
+}
+
+// No session or an expired save both mean "start fresh". Either answer
+// depends on the session cookie, so it must never be stored by a shared
+// cache.
+async fn restore(req: HttpRequest, autosaves: web::Data<Autosaves>) -> impl Responder {
+    let no_store = CacheControl(vec![CacheDirective::Private, CacheDirective::NoStore]);
+    match session_id(&req).and_then(|session| autosaves.load(&session)) {
+        Some(game) => HttpResponse::Ok()
+            .insert_header(no_store)
+            .content_type(ContentType::json())
+            .body(game),
+        None => HttpResponse::NoContent().insert_header(no_store).finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_store_refuses_new_sessions_but_not_existing_ones() {
+        let autosaves = Autosaves::default();
+        let player = "a".repeat(SESSION_ID_LEN);
+        let newcomer = "b".repeat(SESSION_ID_LEN);
+        let huge = "0".repeat(MAX_TOTAL_BYTES - SESSION_ID_LEN);
+
+        assert!(autosaves.store(player.clone(), huge));
+        assert!(!autosaves.store(newcomer.clone(), "{}".to_string()));
+
+        // Overwriting frees the old save's space first.
+        assert!(autosaves.store(player.clone(), "{}".to_string()));
+        assert!(autosaves.store(newcomer, "{}".to_string()));
+        assert_eq!(autosaves.load(&player).as_deref(), Some("{}"));
+        assert_eq!(
+            autosaves.store.lock().unwrap().bytes,
+            2 * (SESSION_ID_LEN + 2)
+        );
+    }
+}
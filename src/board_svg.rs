@@ -0,0 +1,188 @@
+use crate::palette;
+use actix_web::error::InternalError;
+use actix_web::http::header::{ContentType, X_CONTENT_TYPE_OPTIONS};
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::fmt::Write;
+
+const SIZE: usize = 4;
+const CELL: usize = 100;
+const GAP: usize = 7;
+const BOARD: usize = SIZE * CELL + (SIZE + 1) * GAP;
+
+type Cells = [[u64; SIZE]; SIZE];
+
+#[derive(Deserialize)]
+struct BoardQuery {
+    board: String,
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/board.svg")
+            // A missing or malformed query is just another invalid board.
+            .app_data(web::QueryConfig::default().error_handler(|err, _| {
+                let res = unprocessable(err.to_string());
+                InternalError::from_response(err, res).into()
+            }))
+            .route(web::get().to(board_svg)),
+    );
+}
+
+// The message can quote the submitted board, so make sure a browser never
+// treats it as HTML on our origin.
+fn unprocessable(message: String) -> HttpResponse {
+    HttpResponse::UnprocessableEntity()
+        .content_type(ContentType::plaintext())
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(message)
+}
+
+// Parses the shareable board format: rows separated by `;`, cells by `,`,
+// e.g. `2,2,0,0;0,4,0,0;0,0,0,0;0,0,0,8`.
+fn parse_board(board: &str) -> Result<Cells, String> {
+    let mut cells = [[0; SIZE]; SIZE];
+    let rows: Vec<&str> = board.split(';').collect();
+    if rows.len() != SIZE {
+        return Err(format!("expected {} rows, got {}", SIZE, rows.len()));
+    }
+    for (y, row) in rows.iter().enumerate() {
+        let values: Vec<&str> = row.split(',').collect();
+        if values.len() != SIZE {
+            return Err(format!(
+                "row {}: expected {} cells, got {}",
+                y,
+                SIZE,
+                values.len()
+            ));
+        }
+        for (x, value) in values.iter().enumerate() {
+            let value: u64 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("row {}: {:?} is not a number", y, value))?;
+            if value == 1 || (value != 0 && !value.is_power_of_two()) {
+                return Err(format!("row {}: {} is not a tile value", y, value));
+            }
+            cells[y][x] = value;
+        }
+    }
+    Ok(cells)
+}
+
+fn font_size(value: u64) -> usize {
+    match value.to_string().len() {
+        0..=2 => 48,
+        3 => 40,
+        4 => 32,
+        _ => 24,
+    }
+}
+
+fn render(cells: &Cells) -> String {
+    let mut svg = String::new();
+    // Writing to a String cannot fail.
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+        BOARD
+    );
+    let _ = write!(
+        svg,
+        r#"<rect width="{0}" height="{0}" rx="15" fill="{1}"/>"#,
+        BOARD,
+        palette::BOARD_BACKGROUND
+    );
+    for (y, row) in cells.iter().enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            let left = GAP + x * (CELL + GAP);
+            let top = GAP + y * (CELL + GAP);
+            let _ = write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{2}" height="{2}" rx="7.5" fill="{3}"/>"#,
+                left,
+                top,
+                CELL,
+                palette::background(value)
+            );
+            if value != 0 {
+                let _ = write!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-family="sans-serif" font-weight="bold" font-size="{}" fill="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    left + CELL / 2,
+                    top + CELL / 2,
+                    font_size(value),
+                    palette::text(value),
+                    value
+                );
+            }
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+async fn board_svg(query: web::Query<BoardQuery>) -> impl Responder {
+    match parse_board(&query.board) {
+        Ok(cells) => HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .body(render(&cells)),
+        Err(err) => unprocessable(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::http::StatusCode;
+    use actix_web::{test as actix_test, App};
+
+    #[test]
+    fn parses_a_valid_board() {
+        let cells = parse_board("2,2,0,0;0,4,0,0;0,0,0,0;0,0,0,8").unwrap();
+        assert_eq!(cells[0], [2, 2, 0, 0]);
+        assert_eq!(cells[3], [0, 0, 0, 8]);
+    }
+
+    #[test]
+    fn rejects_wrong_row_count() {
+        let err = parse_board("0,0,0,0;0,0,0,0;0,0,0,0").unwrap_err();
+        assert_eq!(err, "expected 4 rows, got 3");
+    }
+
+    #[test]
+    fn rejects_wrong_cell_count() {
+        let err = parse_board("0,0,0,0;0,0,0;0,0,0,0;0,0,0,0").unwrap_err();
+        assert_eq!(err, "row 1: expected 4 cells, got 3");
+    }
+
+    #[test]
+    fn rejects_non_power_of_two() {
+        let err = parse_board("0,0,0,0;0,0,0,0;0,0,6,0;0,0,0,0").unwrap_err();
+        assert_eq!(err, "row 2: 6 is not a tile value");
+    }
+
+    #[test]
+    fn rejects_one() {
+        let err = parse_board("1,0,0,0;0,0,0,0;0,0,0,0;0,0,0,0").unwrap_err();
+        assert_eq!(err, "row 0: 1 is not a tile value");
+    }
+
+    #[actix_web::test]
+    async fn missing_query_is_422_plain_text() {
+        let app = actix_test::init_service(App::new().configure(routes)).await;
+        let req = actix_test::TestRequest::get()
+            .uri("/board.svg")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let headers = res.headers();
+        assert_eq!(
+            headers.get(CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+    }
+}
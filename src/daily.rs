@@ -0,0 +1,69 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use time::{Date, OffsetDateTime};
+
+#[derive(Serialize)]
+struct DailyChallenge {
+    date: String,
+    // 32 bits so the value survives a round trip through JavaScript numbers.
+    seed: u32,
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/daily", web::get().to(daily));
+}
+
+// FNV-1a over the yyyymmdd string. Unlike std's hashers its output is
+// fixed forever, so a given day maps to the same seed across deploys and
+// Rust versions.
+fn seed_for(date: Date) -> u32 {
+    let key = format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    );
+    key.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    })
+}
+
+// Everyone playing on the same UTC day gets the same seed, and so the same
+// opening and spawn sequence.
+async fn daily() -> impl Responder {
+    let today = OffsetDateTime::now_utc().date();
+    HttpResponse::Ok()
+        // The seed changes at midnight UTC, so don't let it be cached.
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(DailyChallenge {
+            date: format!(
+                "{:04}-{:02}-{:02}",
+                today.year(),
+                u8::from(today.month()),
+                today.day()
+            ),
+            seed: seed_for(today),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Month;
+
+    // Clients already derive boards from served seeds; changing the hash
+    // would silently hand everyone a different daily game.
+    #[test]
+    fn seed_is_stable() {
+        let date = Date::from_calendar_date(2026, Month::October, 16).unwrap();
+        assert_eq!(seed_for(date), 3900397293);
+    }
+
+    #[test]
+    fn different_days_get_different_seeds() {
+        let today = Date::from_calendar_date(2026, Month::October, 16).unwrap();
+        let tomorrow = today.next_day().unwrap();
+        assert_ne!(seed_for(today), seed_for(tomorrow));
+    }
+}
@@ -1,25 +1,388 @@
-use actix_files::Files;
-use actix_web::{App, HttpServer};
+use actix_cors::Cors;
+use actix_files::{Files, NamedFile};
+use actix_web::dev::{
+    fn_service, RequestHead, Service, ServiceFactory, ServiceRequest, ServiceResponse,
+};
+use actix_web::http::header::{self, HeaderValue, CACHE_CONTROL};
+use actix_web::http::{Method, StatusCode, Uri};
+use actix_web::middleware::{Compress, Logger};
+use actix_web::{web, App, HttpResponse, HttpServer, Scope};
+use futures_util::future::{self, Either};
+use rate_limit::{ClientIp, RateLimiter};
 use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+mod autosave;
+mod board_svg;
+mod daily;
+mod palette;
+mod rate_limit;
+mod report;
+mod spectate;
+
+// Resolve the folder holding index.html and the wasm bundle. An explicit
+// STATIC_DIR wins; otherwise look next to the executable, then in the
+// current working directory.
+fn resolve_static_dir() -> io::Result<PathBuf> {
+    let candidates = match env::var_os("STATIC_DIR") {
+        Some(dir) => vec![PathBuf::from(dir)],
+        None => {
+            let mut dirs = Vec::new();
+            if let Some(exe_dir) = env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            {
+                dirs.push(exe_dir.join("static"));
+            }
+            dirs.push(env::current_dir()?.join("static"));
+            dirs
+        }
+    };
+
+    candidates
+        .iter()
+        .find(|dir| dir.join("index.html").is_file())
+        .cloned()
+        .ok_or_else(|| {
+            let tried: Vec<String> = candidates.iter().map(|d| d.display().to_string()).collect();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no index.html found in static directory (tried: {}); set STATIC_DIR",
+                    tried.join(", ")
+                ),
+            )
+        })
+}
+
+const DEFAULT_PORT: u16 = 8000;
+
+// PORT may be unset or injected empty by a container runtime; both mean
+// the default. Anything else must be a usable port number.
+fn port_from_env() -> io::Result<u16> {
+    let port = match env::var("PORT") {
+        Ok(port) if !port.trim().is_empty() => port,
+        _ => return Ok(DEFAULT_PORT),
+    };
+    match port.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("PORT must be a number between 1 and 65535, got {:?}", port),
+        )),
+        Ok(port) => Ok(port),
+    }
+}
+
+// Numeric settings may be unset or injected empty by a container runtime;
+// both mean the default. Anything else must parse as a non-zero `T`.
+fn positive_from_env<T: FromStr + PartialOrd + Default>(name: &str, default: T) -> io::Result<T> {
+    let value = match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value,
+        _ => return Ok(default),
+    };
+    match value.trim().parse::<T>() {
+        Ok(parsed) if parsed > T::default() => Ok(parsed),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} must be a positive integer, got {:?}", name, value),
+        )),
+    }
+}
+
+// Per-IP budget for POSTs to the API, meant for score submission and
+// verification. IPv6 clients are limited per /64.
+//
+// Behind a reverse proxy every request comes from the proxy's address;
+// set TRUST_X_FORWARDED_FOR=1 to limit by the client address the proxy
+// appends to X-Forwarded-For instead. Leave it unset when clients connect
+// directly, or they can pick their own address.
+const DEFAULT_API_RATE_LIMIT: u32 = 30;
+const DEFAULT_API_RATE_WINDOW_SECS: u64 = 60;
+
+// Autosave is posted every few moves and reports may come in bursts; both
+// have their own buckets, so neither counts against the API budget.
+const UNLIMITED_API_PATHS: [&str; 2] = ["/api/autosave", "/api/report"];
+
+// API_RATE_LIMIT POSTs per API_RATE_WINDOW_SECS seconds.
+fn api_limiter_from_env() -> io::Result<RateLimiter> {
+    let limit = positive_from_env("API_RATE_LIMIT", DEFAULT_API_RATE_LIMIT)?;
+    let window = positive_from_env("API_RATE_WINDOW_SECS", DEFAULT_API_RATE_WINDOW_SECS)?;
+    Ok(RateLimiter::new(limit, Duration::from_secs(window)))
+}
+
+// Unset, empty, `0` or `false` mean off; `1` or `true` mean on.
+fn flag_from_env(name: &str) -> io::Result<bool> {
+    match env::var(name).unwrap_or_default().trim() {
+        "" | "0" | "false" => Ok(false),
+        "1" | "true" => Ok(true),
+        value => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} must be 1, true, 0 or false, got {:?}", name, value),
+        )),
+    }
+}
+
+fn client_ip_from_env() -> io::Result<ClientIp> {
+    flag_from_env("TRUST_X_FORWARDED_FOR").map(ClientIp::new)
+}
+
+// The /api scope with its POST rate limit applied; routes are added by
+// the caller.
+fn api_scope(
+    limiter: web::Data<RateLimiter>,
+    client_ip: ClientIp,
+) -> Scope<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    // Reads are cheap and idempotent; only writes are limited.
+    web::scope("/api").wrap_fn(move |req, srv| {
+        let limited = req.method() == Method::POST
+            && !UNLIMITED_API_PATHS.contains(&req.path())
+            && client_ip
+                .of(req.request())
+                .is_some_and(|ip| !limiter.check(ip));
+        if limited {
+            let res = HttpResponse::TooManyRequests().finish();
+            Either::Left(future::ok(req.into_response(res)))
+        } else {
+            Either::Right(srv.call(req))
+        }
+    })
+}
+
+// Extensions of the files the build emits. Only these count as asset
+// requests; anything else (`/`, `/replay/abc`, `/replay/v1.2`) is a page
+// the wasm app routes itself.
+const ASSET_EXTENSIONS: [&str; 8] = [
+    ".js", ".wasm", ".css", ".json", ".ico", ".png", ".map", ".ts",
+];
+
+fn is_asset_path(path: &str) -> bool {
+    ASSET_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+// Unknown page paths get index.html so client-side routes survive a
+// refresh. Missing assets still 404 rather than returning HTML.
+async fn spa_fallback(req: ServiceRequest, index: PathBuf) -> actix_web::Result<ServiceResponse> {
+    let (req, _) = req.into_parts();
+    if is_asset_path(req.path()) {
+        return Ok(ServiceResponse::new(req, HttpResponse::NotFound().finish()));
+    }
+    let res = NamedFile::open_async(index).await?.into_response(&req);
+    Ok(ServiceResponse::new(req, res))
+}
+
+// Asset filenames are not content-hashed, so other assets only get a
+// short lifetime. Pages and the game bundle are always revalidated
+// (actix-files answers with ETag/Last-Modified, so that is usually a 304):
+// the JS glue and the wasm it loads must come from the same deploy, and
+// the wasm URL carries no version to bust a stale copy.
+const ASSET_CACHE_CONTROL: &str = "public, max-age=3600";
+const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+
+fn cache_control_for(path: &str) -> &'static str {
+    let revalidate = [".html", ".js", ".wasm"]
+        .iter()
+        .any(|ext| path.ends_with(ext));
+    if revalidate || !is_asset_path(path) {
+        REVALIDATE_CACHE_CONTROL
+    } else {
+        ASSET_CACHE_CONTROL
+    }
+}
+
+// Accept requests from the page's own origin and from local dev servers
+// (any port on localhost/127.0.0.1).
+fn is_same_origin_or_localhost(origin: &HeaderValue, req: &RequestHead) -> bool {
+    let authority = match origin.to_str().ok().and_then(|o| o.split_once("://")) {
+        Some((_, authority)) => authority,
+        None => return false,
+    };
+    let same_origin = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .is_some_and(|host| host == authority);
+    let host = authority.split(':').next().unwrap_or(authority);
+    same_origin || host == "localhost" || host == "127.0.0.1"
+}
+
+// ALLOWED_ORIGIN must be a single scheme://host[:port] origin. Unset or
+// empty means the default rule. A trailing slash is dropped, since
+// browsers never send one and the origin would otherwise never match.
+fn allowed_origin_from_env() -> io::Result<Option<String>> {
+    let value = match env::var("ALLOWED_ORIGIN") {
+        Ok(value) if !value.trim().is_empty() => value,
+        _ => return Ok(None),
+    };
+    let origin = value.trim().trim_end_matches('/');
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "ALLOWED_ORIGIN must be an origin like http://localhost:3000, got {:?}",
+                value
+            ),
+        )
+    };
+    let uri = origin.parse::<Uri>().map_err(|_| invalid())?;
+    match (uri.scheme_str(), uri.authority()) {
+        (Some(scheme @ ("http" | "https")), Some(authority))
+            if uri.path().len() <= 1 && uri.query().is_none() =>
+        {
+            Ok(Some(format!("{}://{}", scheme, authority)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// CORS policy for the JSON API. ALLOWED_ORIGIN (e.g. http://localhost:3000)
+// replaces the default same-origin/localhost rule and may send credentials,
+// so autosave works from it as long as it is same-site (the session cookie
+// is SameSite=Strict). The default rule sends none: any page on localhost
+// could otherwise read a player's save, so there autosave is same-origin
+// only.
+fn cors(allowed_origin: Option<&str>) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(3600);
+    match allowed_origin {
+        Some(origin) => cors.allowed_origin(origin).supports_credentials(),
+        None => cors.allowed_origin_fn(is_same_origin_or_localhost),
+    }
+}
+
+const HEALTH_CHECK_PATH: &str = "/healthz";
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let host = "0.0.0.0";
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "8000".to_string())
-        .parse::<u16>()
-        .expect("Failed to parse PORT variable");
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
-    // Get the current working directory
-    let current_dir = env::current_dir().expect("Failed to get current directory");
+    let host = "0.0.0.0";
+    // Startup errors are logged as well as returned so they show up next to
+    // the rest of the server's output.
+    let port = port_from_env().inspect_err(|err| log::error!("{}", err))?;
+    let static_dir = resolve_static_dir().inspect_err(|err| log::error!("{}", err))?;
+    let api_limiter =
+        web::Data::new(api_limiter_from_env().inspect_err(|err| log::error!("{}", err))?);
+    let client_ip = client_ip_from_env().inspect_err(|err| log::error!("{}", err))?;
+    let allowed_origin = allowed_origin_from_env().inspect_err(|err| log::error!("{}", err))?;
+    log::info!("Serving static files from {}", static_dir.display());
 
-    // Build the path to the "static" folder
-    let static_dir = current_dir.join("static");
+    let sessions = web::Data::new(spectate::Sessions::default());
+    let autosaves = web::Data::new(autosave::Autosaves::default());
+    let autosave_limiter = web::Data::new(autosave::limiter());
+    let report_limiter = web::Data::new(report::limiter());
+    autosave::spawn_sweeper(autosaves.clone());
 
-    HttpServer::new(move || {
-        App::new().service(Files::new("/", static_dir.clone()).index_file("index.html"))
+    let server = HttpServer::new(move || {
+        App::new()
+            // Negotiates gzip/brotli/zstd from Accept-Encoding; the wasm bundle
+            // compresses very well. actix-files already serves .wasm as
+            // application/wasm, which streaming instantiation requires.
+            .wrap(Compress::default())
+            // JSON endpoints live under /api; only they get CORS handling, so
+            // static file serving is unaffected.
+            .service(
+                // CORS wraps the rate limit so 429s still carry CORS headers.
+                api_scope(api_limiter.clone(), client_ip)
+                    .wrap(cors(allowed_origin.as_deref()))
+                    .app_data(web::Data::new(client_ip))
+                    .app_data(autosaves.clone())
+                    .configure(|cfg| autosave::routes(cfg, autosave_limiter.clone()))
+                    .configure(board_svg::routes)
+                    .configure(daily::routes)
+                    .configure(spectate::api_routes)
+                    .configure(|cfg| report::routes(cfg, report_limiter.clone())),
+            )
+            .app_data(sessions.clone())
+            .configure(spectate::routes)
+            .route(HEALTH_CHECK_PATH, web::get().to(HttpResponse::Ok))
+            // Everything not matched above is a static file or a page. Only
+            // these get the cache policy; API handlers set their own.
+            .service(
+                web::scope("")
+                    .wrap_fn(|req, srv| {
+                        let cache_control = cache_control_for(req.path());
+                        let fut = srv.call(req);
+                        async move {
+                            let mut res = fut.await?;
+                            // Handlers that set their own policy keep it.
+                            let cacheable = res.status().is_success()
+                                || res.status() == StatusCode::NOT_MODIFIED;
+                            if cacheable && !res.headers().contains_key(CACHE_CONTROL) {
+                                res.headers_mut()
+                                    .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+                            }
+                            Ok(res)
+                        }
+                    })
+                    .service(
+                        Files::new("/", static_dir.clone())
+                            .index_file("index.html")
+                            .default_handler(fn_service({
+                                let index = static_dir.join("index.html");
+                                move |req| spa_fallback(req, index.clone())
+                            })),
+                    ),
+            )
+            // Method, path, status, size and latency per request. Registered
+            // last so it is outermost: %b is the compressed size and %D
+            // covers every other middleware. Health-check probes hit /healthz
+            // constantly, so keep them out of the log.
+            .wrap(Logger::new("%r %s %b %Dms").exclude(HEALTH_CHECK_PATH))
     })
-    .bind((host, port))?
-    .run()
-    .await
+    .bind((host, port))?;
+
+    for addr in server.addrs() {
+        log::info!("Listening on http://{}", addr);
+    }
+
+    server.run().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn api_posts_past_the_limit_get_429() {
+        let limiter = web::Data::new(RateLimiter::new(3, Duration::from_secs(60)));
+        let app = test::init_service(
+            App::new().service(
+                api_scope(limiter, ClientIp::new(false))
+                    .route("/scores", web::post().to(HttpResponse::Ok))
+                    .route("/autosave", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+        let post = |path: &str| {
+            test::TestRequest::post()
+                .uri(path)
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .to_request()
+        };
+
+        for _ in 0..3 {
+            let res = test::call_service(&app, post("/api/scores")).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let res = test::call_service(&app, post("/api/scores")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Exempt routes are unaffected once the budget is spent.
+        let res = test::call_service(&app, post("/api/autosave")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
 }
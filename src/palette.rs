@@ -0,0 +1,35 @@
+// Tile colours, kept in step with get_color_for_cell in the game2048
+// frontend so server-rendered boards look like the real thing.
+
+pub const BOARD_BACKGROUND: &str = "#cccccc";
+pub const EMPTY_CELL: &str = "rgba(238, 228, 218, 0.35)";
+
+const DARK_TEXT: &str = "#6c6462";
+const LIGHT_TEXT: &str = "#ffffff";
+
+// Background for a tile value; everything past 2048 shares one colour.
+pub fn background(value: u64) -> &'static str {
+    match value {
+        0 => EMPTY_CELL,
+        2 => "#eee4da",
+        4 => "#ede0c8",
+        8 => "#f2b179",
+        16 => "#f59563",
+        32 => "#f67c5f",
+        64 => "#f65e3b",
+        128 => "#edcf72",
+        256 => "#edcc61",
+        512 => "#edc850",
+        1024 => "#edc53f",
+        2048 => "#edc22e",
+        _ => "#3c3a32",
+    }
+}
+
+pub fn text(value: u64) -> &'static str {
+    if value <= 4 {
+        DARK_TEXT
+    } else {
+        LIGHT_TEXT
+    }
+}
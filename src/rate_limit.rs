@@ -0,0 +1,167 @@
+use actix_web::http::header::X_FORWARDED_FOR;
+use actix_web::HttpRequest;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Hard cap on tracked clients. When full, idle buckets are swept out at
+// most once per window (a bucket untouched for a whole window is full
+// again, so forgetting it changes nothing); until a sweep frees room, new
+// clients are refused rather than letting the map grow.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// Where a request's client address comes from. Behind a reverse proxy
+// every request shares the proxy's address, so the proxy's
+// X-Forwarded-For is used instead, but only when explicitly trusted:
+// clients can send that header themselves.
+#[derive(Clone, Copy)]
+pub struct ClientIp {
+    trust_forwarded_for: bool,
+}
+
+impl ClientIp {
+    pub fn new(trust_forwarded_for: bool) -> ClientIp {
+        ClientIp {
+            trust_forwarded_for,
+        }
+    }
+
+    // The proxy appends the address it saw, so only the last entry is
+    // trustworthy; earlier ones are whatever the client sent.
+    pub fn of(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| req.headers().get_all(X_FORWARDED_FOR).last())
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|last| last.trim().parse().ok());
+        forwarded.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+    }
+}
+
+// An IPv6 host is usually handed a whole /64 and can pick any address in
+// it, so the prefix is what gets limited.
+fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (!0 << 64))),
+        },
+        v4 => v4,
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    clients: HashMap<IpAddr, Bucket>,
+    last_sweep: Instant,
+}
+
+// Per-client token bucket: each client may burst up to `capacity` requests,
+// refilled at `capacity` tokens per `window`.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    window: Duration,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, window: Duration) -> RateLimiter {
+        RateLimiter {
+            capacity: f64::from(capacity),
+            refill_per_sec: f64::from(capacity) / window.as_secs_f64(),
+            window,
+            buckets: Mutex::new(Buckets {
+                clients: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    // Takes a token for `ip`, returning false if it has none left.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let ip = bucket_key(ip);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            clients,
+            last_sweep,
+        } = &mut *buckets;
+
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&ip) {
+            if now.duration_since(*last_sweep) < self.window {
+                return false;
+            }
+            clients.retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+            *last_sweep = now;
+            if clients.len() >= MAX_TRACKED_CLIENTS {
+                return false;
+            }
+        }
+
+        let bucket = clients.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn refuses_new_clients_when_full() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        for n in 0..MAX_TRACKED_CLIENTS as u32 {
+            assert!(limiter.check(IpAddr::V4(Ipv4Addr::from(n))));
+        }
+        let newcomer = IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255));
+        assert!(!limiter.check(newcomer));
+        assert_eq!(
+            limiter.buckets.lock().unwrap().clients.len(),
+            MAX_TRACKED_CLIENTS
+        );
+    }
+
+    #[test]
+    fn ipv6_clients_share_a_bucket_per_64() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.check("2001:db8:1:2::1".parse().unwrap()));
+        assert!(!limiter.check("2001:db8:1:2:ffff::9".parse().unwrap()));
+        assert!(limiter.check("2001:db8:1:3::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn forwarded_for_is_only_used_when_trusted() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, "6.6.6.6, 203.0.113.7"))
+            .to_http_request();
+        let proxied: IpAddr = "203.0.113.7".parse().unwrap();
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(ClientIp::new(true).of(&req), Some(proxied));
+        assert_eq!(ClientIp::new(false).of(&req), Some(peer));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::rate_limit::{ClientIp, RateLimiter};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+// A panic message plus a little context comfortably fits; anything larger
+// is rejected before it reaches the handler.
+const MAX_REPORT_BYTES: usize = 4 * 1024;
+
+// A crashing client may retry or crash in a loop; a few reports per
+// minute are plenty to diagnose it.
+const REPORTS_PER_WINDOW: u32 = 5;
+const REPORT_WINDOW: Duration = Duration::from_secs(60);
+
+// Sent by the wasm panic hook after it has logged to the browser console.
+#[derive(Deserialize)]
+struct PanicReport {
+    message: String,
+    context: Option<Value>,
+}
+
+pub fn limiter() -> RateLimiter {
+    RateLimiter::new(REPORTS_PER_WINDOW, REPORT_WINDOW)
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig, limiter: web::Data<RateLimiter>) {
+    cfg.service(
+        web::resource("/report")
+            .app_data(limiter)
+            .app_data(web::JsonConfig::default().limit(MAX_REPORT_BYTES))
+            .route(web::post().to(report)),
+    );
+}
+
+async fn report(
+    req: HttpRequest,
+    limiter: web::Data<RateLimiter>,
+    client_ip: web::Data<ClientIp>,
+    report: web::Json<PanicReport>,
+) -> impl Responder {
+    let ip = match client_ip.of(&req) {
+        Some(ip) => ip,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    if !limiter.check(ip) {
+        return HttpResponse::TooManyRequests().finish();
+    }
+
+    // The message is client-controlled; Debug escapes newlines and other
+    // control characters so it can't forge extra log lines. The context is
+    // already escaped by serde_json's Display.
+    match &report.context {
+        Some(context) => log::error!(
+            "client panic from {}: {:?} ({})",
+            ip,
+            report.message,
+            context
+        ),
+        None => log::error!("client panic from {}: {:?}", ip, report.message),
+    }
+    HttpResponse::NoContent().finish()
+}
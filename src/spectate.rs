@@ -0,0 +1,335 @@
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use futures_util::stream;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+// Board updates kept for a slow spectator. One that falls further behind
+// loses the oldest updates and resumes from the oldest one still kept.
+const CHANNEL_CAPACITY: usize = 16;
+const MAX_SPECTATORS_PER_SESSION: usize = 32;
+
+// Games are created by the server, which hands the player a secret token
+// for the play socket; spectators only need the id. A game nobody starts
+// playing is forgotten after a minute.
+const MAX_GAMES: usize = 1_000;
+const GAME_ID_LEN: usize = 16;
+const UNSTARTED_GAME_TTL: Duration = Duration::from_secs(60);
+
+// A board update is a few hundred bytes of JSON; anything much larger is
+// not one.
+const MAX_FRAME_BYTES: usize = 4 * 1024;
+
+// Sockets that die without a Close frame (sleeping phone, dropped network)
+// are noticed by pinging and giving up when nothing comes back.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+trait Heartbeat: Actor<Context = ws::WebsocketContext<Self>> {
+    fn last_heartbeat(&self) -> Instant;
+}
+
+fn start_heartbeat<A: Heartbeat>(ctx: &mut ws::WebsocketContext<A>) {
+    ctx.run_interval(HEARTBEAT_INTERVAL, |actor, ctx| {
+        if actor.last_heartbeat().elapsed() > CLIENT_TIMEOUT {
+            ctx.stop();
+            return;
+        }
+        ctx.ping(b"");
+    });
+}
+
+struct Game {
+    token: String,
+    tx: broadcast::Sender<String>,
+    created: Instant,
+    playing: bool,
+}
+
+impl Game {
+    fn is_live(&self) -> bool {
+        self.playing || self.created.elapsed() < UNSTARTED_GAME_TTL
+    }
+}
+
+// Games keyed by id. Each one is a broadcast channel fed by the player's
+// socket and drained by every spectator socket.
+#[derive(Default)]
+pub struct Sessions {
+    games: Mutex<HashMap<String, Game>>,
+}
+
+impl Sessions {
+    fn create(&self) -> Option<NewGame> {
+        let mut games = self.games.lock().unwrap();
+        if games.len() >= MAX_GAMES {
+            games.retain(|_, game| game.is_live());
+            if games.len() >= MAX_GAMES {
+                return None;
+            }
+        }
+        let mut rng = rand::thread_rng();
+        let id = format!("{:016x}", rng.gen::<u64>());
+        let token = format!("{:032x}", rng.gen::<u128>());
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let game = Game {
+            token: token.clone(),
+            tx,
+            created: Instant::now(),
+            playing: false,
+        };
+        // A 64-bit collision among a thousand games is not worth a retry.
+        games.insert(id.clone(), game);
+        Some(NewGame { id, token })
+    }
+
+    fn start(&self, id: &str, token: &str) -> Result<broadcast::Sender<String>, HttpResponse> {
+        let mut games = self.games.lock().unwrap();
+        let game = games
+            .get_mut(id)
+            .filter(|game| game.is_live())
+            .ok_or_else(|| HttpResponse::NotFound().body("no such game"))?;
+        if game.token != token {
+            return Err(HttpResponse::Forbidden().body("wrong token"));
+        }
+        if game.playing {
+            return Err(HttpResponse::Conflict().body("game is already being broadcast"));
+        }
+        game.playing = true;
+        Ok(game.tx.clone())
+    }
+
+    fn close(&self, id: &str) {
+        self.games.lock().unwrap().remove(id);
+    }
+
+    fn subscribe(&self, id: &str) -> Result<broadcast::Receiver<String>, HttpResponse> {
+        let games = self.games.lock().unwrap();
+        let game = games
+            .get(id)
+            .filter(|game| game.is_live())
+            .ok_or_else(|| HttpResponse::NotFound().body("no such game"))?;
+        if game.tx.receiver_count() >= MAX_SPECTATORS_PER_SESSION {
+            return Err(HttpResponse::ServiceUnavailable().body("too many spectators"));
+        }
+        Ok(game.tx.subscribe())
+    }
+}
+
+#[derive(Serialize)]
+struct NewGame {
+    id: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct PlayQuery {
+    token: String,
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/ws/play/{id}", web::get().to(play))
+        .route("/ws/spectate/{id}", web::get().to(spectate));
+}
+
+// Lives under /api so creating games counts against the API rate limit.
+pub fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/spectate", web::post().to(create));
+}
+
+// The player's socket: every text frame is a board-state JSON blob that
+// gets relayed to the spectators as-is. Anything else ends the game.
+struct Player {
+    id: String,
+    sessions: web::Data<Sessions>,
+    tx: broadcast::Sender<String>,
+    last_heartbeat: Instant,
+}
+
+impl Heartbeat for Player {
+    fn last_heartbeat(&self) -> Instant {
+        self.last_heartbeat
+    }
+}
+
+impl Actor for Player {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        start_heartbeat(ctx);
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        // Dropping the sender ends every spectator stream.
+        self.sessions.close(&self.id);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Player {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.last_heartbeat = Instant::now();
+        match msg {
+            Ok(ws::Message::Text(board)) => {
+                if serde_json::from_str::<Value>(&board).is_err() {
+                    ctx.close(Some(ws::CloseCode::Invalid.into()));
+                    ctx.stop();
+                    return;
+                }
+                // No spectators is not an error.
+                let _ = self.tx.send(board.to_string());
+            }
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(_) => ctx.stop(),
+        }
+    }
+}
+
+struct Spectator {
+    rx: Option<broadcast::Receiver<String>>,
+    last_heartbeat: Instant,
+}
+
+impl Heartbeat for Spectator {
+    fn last_heartbeat(&self) -> Instant {
+        self.last_heartbeat
+    }
+}
+
+impl Actor for Spectator {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        start_heartbeat(ctx);
+        if let Some(rx) = self.rx.take() {
+            ctx.add_stream(stream::unfold(rx, |mut rx| async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(board) => return Some((board, rx)),
+                        // Missed some moves; carry on from the oldest kept.
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }));
+        }
+    }
+}
+
+impl StreamHandler<String> for Spectator {
+    fn handle(&mut self, board: String, ctx: &mut Self::Context) {
+        ctx.text(board);
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        // The player left.
+        ctx.close(Some(ws::CloseCode::Normal.into()));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Spectator {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.last_heartbeat = Instant::now();
+        match msg {
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(_) => ctx.stop(),
+        }
+    }
+}
+
+// The token is a secret, so the answer must never be cached.
+async fn create(sessions: web::Data<Sessions>) -> HttpResponse {
+    match sessions.create() {
+        Some(game) => HttpResponse::Ok()
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .json(game),
+        None => HttpResponse::ServiceUnavailable().body("too many games"),
+    }
+}
+
+async fn play(
+    req: HttpRequest,
+    stream: web::Payload,
+    id: web::Path<String>,
+    query: web::Query<PlayQuery>,
+    sessions: web::Data<Sessions>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    if id.len() != GAME_ID_LEN {
+        return Ok(HttpResponse::NotFound().body("no such game"));
+    }
+    let tx = match sessions.start(&id, &query.token) {
+        Ok(tx) => tx,
+        Err(res) => return Ok(res),
+    };
+    let player = Player {
+        id: id.clone(),
+        sessions: sessions.clone(),
+        tx,
+        last_heartbeat: Instant::now(),
+    };
+    // If the handshake fails the actor never runs, so clean up here.
+    ws::WsResponseBuilder::new(player, &req, stream)
+        .frame_size(MAX_FRAME_BYTES)
+        .start()
+        .inspect_err(|_| sessions.close(&id))
+}
+
+async fn spectate(
+    req: HttpRequest,
+    stream: web::Payload,
+    id: web::Path<String>,
+    sessions: web::Data<Sessions>,
+) -> Result<HttpResponse, Error> {
+    if id.len() != GAME_ID_LEN {
+        return Ok(HttpResponse::NotFound().body("no such game"));
+    }
+    let rx = match sessions.subscribe(&id) {
+        Ok(rx) => rx,
+        Err(res) => return Ok(res),
+    };
+    let spectator = Spectator {
+        rx: Some(rx),
+        last_heartbeat: Instant::now(),
+    };
+    // Spectators only send control frames.
+    ws::WsResponseBuilder::new(spectator, &req, stream)
+        .frame_size(MAX_FRAME_BYTES)
+        .start()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn only_the_token_holder_can_play_once() {
+        let sessions = Sessions::default();
+        let game = sessions.create().unwrap();
+        assert_eq!(game.id.len(), GAME_ID_LEN);
+
+        let err = sessions.start(&game.id, "guess").unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        assert!(sessions.start(&game.id, &game.token).is_ok());
+        let err = sessions.start(&game.id, &game.token).unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+}